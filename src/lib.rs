@@ -1,4 +1,15 @@
-#![recursion_limit = "256"]
+#![recursion_limit = "512"]
+
+//! The code generated by `#[narrowable(...)]`, `#[narrowable_vec(...)]`, and
+//! `#[narrowable_rboehm(...)]` stores trait object vtables as `core::ptr::DynMetadata` and
+//! reconstructs `dyn` references with `core::ptr::from_raw_parts`. Those APIs are gated behind
+//! the unstable `ptr_metadata` feature, so any crate that applies one of these attributes must be
+//! built with a nightly toolchain and must itself enable `#![feature(ptr_metadata)]`.
+//!
+//! The struct generated by `#[narrowable(...)]` is additionally generic over
+//! `core::alloc::Allocator` (defaulting to `Global`), which is itself gated behind
+//! `#![feature(allocator_api)]` -- any crate applying `#[narrowable(...)]` must enable that
+//! feature too, even if it only uses the `Global`-backed `new`.
 
 extern crate proc_macro;
 
@@ -19,32 +30,48 @@ pub fn narrowable(args: TokenStream, input: TokenStream) -> TokenStream {
     };
     let trait_id = &input.ident;
     let expanded = quote! {
-        /// A narrow pointer to #trait_id.
+        /// A narrow pointer to #trait_id, allocated through `A` (the global allocator by
+        /// default).
         #[repr(C)]
-        struct #struct_id {
-            // A pointer to an object; immediately preceding that object is a usized pointer to the
-            // object's vtable. In other words, on a 64 bit machine the layout is (in bytes):
-            //   -8..-1: vtable
+        struct #struct_id<A: ::core::alloc::Allocator = ::std::alloc::Global> {
+            // A pointer to an object; immediately preceding that object is a usize-sized slot
+            // holding the object's `DynMetadata`. In other words, on a 64 bit machine the layout
+            // is (in bytes):
+            //   -8..-1: metadata
             //   0..: object
             // Note that:
             //   1) Depending on the alignment of `object`, the allocated block of memory might
             //      start *before* -8 bytes. To calculate the beginning of the block of memory you
-            //      need to know the alignment of both the vtable pointer and `object` (see
+            //      need to know the alignment of both the metadata and `object` (see
             //      `Drop::drop` below).
             //   2) If `object` is zero-sized the pointer might be to the very end of the block, so
             //      you mustn't blindly load bytes from this pointer.
             // The reason for this complex dance is that we're trying to optimise the common case
             // of converting this thin pointer into a fat pointer. However, we can only know
-            // `object`'s alignment by looking it up in the vtable: if the user doesn't then call
-            // anything in the vtable, we've loaded the vtable's cache line for no good reason.
-            // Using the layout above, we can avoid doing this load entirely except in the less
-            // common case of dropping the pointer.
-            objptr: *mut u8
+            // `object`'s alignment by looking it up in the metadata: if the user doesn't then call
+            // anything through the trait object, we've loaded the metadata's cache line for no
+            // good reason. Using the layout above, we can avoid doing this load entirely except in
+            // the less common case of dropping the pointer.
+            objptr: *mut u8,
+            // Zero-sized for the common case (e.g. `Global`), so `#struct_id` stays exactly one
+            // pointer wide unless the caller picks a stateful allocator.
+            alloc: A
         }
 
-        impl #struct_id {
-            /// Create a new narrow pointer to #trait_id.
+        impl #struct_id<::std::alloc::Global> {
+            /// Create a new narrow pointer to #trait_id, allocated on the global heap.
             pub fn new<U>(v: U) -> Self
+            where
+                *const U: ::std::ops::CoerceUnsized<*const (dyn #trait_id + 'static)>,
+                U: #trait_id + 'static
+            {
+                Self::new_in(v, ::std::alloc::Global)
+            }
+        }
+
+        impl<A: ::core::alloc::Allocator> #struct_id<A> {
+            /// Create a new narrow pointer to #trait_id, allocated through `alloc`.
+            pub fn new_in<U>(v: U, alloc: A) -> Self
             where
                 *const U: ::std::ops::CoerceUnsized<*const (dyn #trait_id + 'static)>,
                 U: #trait_id + 'static
@@ -63,14 +90,21 @@ pub fn narrowable(args: TokenStream, input: TokenStream) -> TokenStream {
                 // The assert below is thus paranoia writ large: it could only trigger if `Layout`
                 // started adding amounts of padding that directly contradict the documentation.
                 debug_assert_eq!(uoff % ::std::mem::align_of::<usize>(), 0);
+                // `DynMetadata` is guaranteed to be a single pointer-sized value, so it fits
+                // exactly in the usize-sized slot the offset arithmetic above reserves.
+                debug_assert_eq!(
+                    ::std::mem::size_of::<::core::ptr::DynMetadata<dyn #trait_id>>(),
+                    ::std::mem::size_of::<usize>()
+                );
 
                 let objptr = unsafe {
-                    let baseptr = ::std::alloc::alloc(layout);
+                    let baseptr = alloc.allocate(layout)
+                        .unwrap_or_else(|_| ::std::alloc::handle_alloc_error(layout))
+                        .as_ptr() as *mut u8;
                     let objptr = baseptr.add(uoff);
-                    let vtableptr = objptr.sub(::std::mem::size_of::<usize>());
-                    let t: &dyn #trait_id = &v;
-                    let vtable = ::std::mem::transmute::<*const dyn #trait_id, (usize, usize)>(t).1;
-                    ::std::ptr::write(vtableptr as *mut usize, vtable);
+                    let header = objptr.sub(::std::mem::size_of::<usize>());
+                    let meta = ::core::ptr::metadata(&v as &dyn #trait_id);
+                    ::std::ptr::write(header as *mut ::core::ptr::DynMetadata<dyn #trait_id>, meta);
                     if ::std::mem::size_of::<U>() != 0 {
                         objptr.copy_from_nonoverlapping(&v as *const U as *const u8,
                             ::std::mem::size_of::<U>());
@@ -80,7 +114,19 @@ pub fn narrowable(args: TokenStream, input: TokenStream) -> TokenStream {
                 ::std::mem::forget(v);
 
                 #struct_id {
-                    objptr
+                    objptr,
+                    alloc
+                }
+            }
+
+            /// Reconstruct a narrow pointer from its raw parts. `objptr` must have been produced
+            /// by [`Self::new_in`] (or another narrow pointer built with the same `A`) and not
+            /// already been recovered or dropped; `alloc` must be the allocator that block was
+            /// allocated from. Failing to uphold either invariant causes undefined behaviour.
+            pub unsafe fn from_raw_parts(objptr: *mut u8, alloc: A) -> Self {
+                #struct_id {
+                    objptr,
+                    alloc
                 }
             }
 
@@ -88,67 +134,335 @@ pub fn narrowable(args: TokenStream, input: TokenStream) -> TokenStream {
             /// `Some(...)` if this narrow trait object has stored an object of type `U` or `None`
             /// otherwise.
             pub fn downcast<U: #trait_id>(&self) -> Option<&U> {
-                let t_vtable = {
-                    let t: *const dyn #trait_id = ::std::ptr::null() as *const U;
-                    unsafe { ::std::mem::transmute::<*const dyn #trait_id, (usize, usize)>(t) }.1
-                };
+                let t_meta = ::core::ptr::metadata(
+                    ::std::ptr::null::<U>() as *const dyn #trait_id);
 
-                let vtable = unsafe {
-                    let vtableptr = self.objptr.sub(::std::mem::size_of::<usize>());
-                    ::std::ptr::read(vtableptr as *mut usize)
+                let meta = unsafe {
+                    let header = self.objptr.sub(::std::mem::size_of::<usize>());
+                    ::std::ptr::read(header as *const ::core::ptr::DynMetadata<dyn #trait_id>)
                 };
 
-                if t_vtable == vtable {
+                if t_meta == meta {
                     Some(unsafe { &*(self.objptr as *const U) })
                 } else {
                     None
                 }
             }
+
+            /// Try casting this narrow trait object to a concrete struct type `U`, returning
+            /// `Some(...)` if this narrow trait object has stored an object of type `U` or `None`
+            /// otherwise.
+            pub fn downcast_mut<U: #trait_id>(&mut self) -> Option<&mut U> {
+                let t_meta = ::core::ptr::metadata(
+                    ::std::ptr::null::<U>() as *const dyn #trait_id);
+
+                let meta = unsafe {
+                    let header = self.objptr.sub(::std::mem::size_of::<usize>());
+                    ::std::ptr::read(header as *const ::core::ptr::DynMetadata<dyn #trait_id>)
+                };
+
+                if t_meta == meta {
+                    Some(unsafe { &mut *(self.objptr as *mut U) })
+                } else {
+                    None
+                }
+            }
+
+            /// Recover the concrete `U` stored behind this narrow pointer by value, if this
+            /// narrow trait object has stored an object of type `U`. On a match, the backing
+            /// storage is deallocated without running `U`'s destructor -- the returned value owns
+            /// it instead. On a mismatch, `self` is returned unchanged in `Err`.
+            pub fn into_inner<U: #trait_id>(self) -> Result<U, Self> {
+                let t_meta = ::core::ptr::metadata(
+                    ::std::ptr::null::<U>() as *const dyn #trait_id);
+
+                let meta = unsafe {
+                    let header = self.objptr.sub(::std::mem::size_of::<usize>());
+                    ::std::ptr::read(header as *const ::core::ptr::DynMetadata<dyn #trait_id>)
+                };
+
+                if t_meta != meta {
+                    return Err(self);
+                }
+
+                let this = ::std::mem::ManuallyDrop::new(self);
+                unsafe {
+                    let v = ::std::ptr::read(this.objptr as *const U);
+
+                    let align = meta.align_of();
+                    let size = meta.size_of();
+                    let (layout, uoff) = ::std::alloc::Layout::new::<usize>().extend(
+                        ::std::alloc::Layout::from_size_align_unchecked(size, align)).unwrap();
+                    let baseptr = this.objptr.sub(uoff);
+                    let alloc = ::std::ptr::read(&this.alloc);
+                    alloc.deallocate(::core::ptr::NonNull::new_unchecked(baseptr), layout);
+
+                    Ok(v)
+                }
+            }
         }
 
-        impl ::std::ops::Deref for #struct_id {
+        impl<A: ::core::alloc::Allocator> ::std::ops::Deref for #struct_id<A> {
             type Target = dyn #trait_id;
 
             fn deref(&self) -> &(dyn #trait_id + 'static) {
                 unsafe {
-                    let vtableptr = self.objptr.sub(::std::mem::size_of::<usize>());
-                    let vtable = ::std::ptr::read(vtableptr as *mut usize);
-                    ::std::mem::transmute::<(*const _, usize), &dyn #trait_id>(
-                        (self.objptr, vtable))
+                    let header = self.objptr.sub(::std::mem::size_of::<usize>());
+                    let meta = ::std::ptr::read(
+                        header as *const ::core::ptr::DynMetadata<dyn #trait_id>);
+                    &*::core::ptr::from_raw_parts(self.objptr as *const (), meta)
                 }
             }
         }
 
-        impl ::std::ops::DerefMut for #struct_id {
+        impl<A: ::core::alloc::Allocator> ::std::ops::DerefMut for #struct_id<A> {
             fn deref_mut(&mut self) -> &mut (dyn #trait_id + 'static) {
                 unsafe {
-                    let vtableptr = self.objptr.sub(::std::mem::size_of::<usize>());
-                    let vtable = ::std::ptr::read(vtableptr as *mut usize);
-                    ::std::mem::transmute::<(*const _, usize), &mut dyn #trait_id>(
-                        (self.objptr, vtable))
+                    let header = self.objptr.sub(::std::mem::size_of::<usize>());
+                    let meta = ::std::ptr::read(
+                        header as *const ::core::ptr::DynMetadata<dyn #trait_id>);
+                    &mut *::core::ptr::from_raw_parts_mut(self.objptr as *mut (), meta)
                 }
             }
         }
 
-        impl ::std::ops::Drop for #struct_id {
+        impl<A: ::core::alloc::Allocator> ::std::ops::Drop for #struct_id<A> {
             fn drop(&mut self) {
-                let fatptr = unsafe {
-                    let vtableptr = self.objptr.sub(::std::mem::size_of::<usize>());
-                    let vtable = ::std::ptr::read(vtableptr as *mut usize);
-                    ::std::mem::transmute::<(*const _, usize), &mut dyn #trait_id>(
-                        (self.objptr, vtable))
+                let meta = unsafe {
+                    let header = self.objptr.sub(::std::mem::size_of::<usize>());
+                    ::std::ptr::read(header as *const ::core::ptr::DynMetadata<dyn #trait_id>)
                 };
+                let fatptr: *mut dyn #trait_id =
+                    ::core::ptr::from_raw_parts_mut(self.objptr as *mut (), meta);
 
                 // Call `drop` on the trait object before deallocating memory.
-                unsafe { ::std::ptr::drop_in_place(fatptr as *mut dyn #trait_id) };
+                unsafe { ::std::ptr::drop_in_place(fatptr) };
 
-                let align = ::std::mem::align_of_val(fatptr);
-                let size = ::std::mem::size_of_val(fatptr);
+                let align = meta.align_of();
+                let size = meta.size_of();
                 unsafe {
                     let (layout, uoff) = ::std::alloc::Layout::new::<usize>().extend(
                         ::std::alloc::Layout::from_size_align_unchecked(size, align)).unwrap();
                     let baseptr = self.objptr.sub(uoff);
-                    ::std::alloc::dealloc(baseptr, layout);
+                    self.alloc.deallocate(::core::ptr::NonNull::new_unchecked(baseptr), layout);
+                }
+            }
+        }
+
+        #input
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Like `narrowable`, but instead of generating a narrow pointer this generates a growable
+/// collection (`#struct_id`) that packs many differently-typed implementors of `#trait_id`
+/// back-to-back in a single allocation, rather than allocating one heap block per object.
+#[proc_macro_attribute]
+pub fn narrowable_vec(args: TokenStream, input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as AttributeArgs);
+    let input = parse_macro_input!(input as ItemTrait);
+    if args.len() != 1 {
+        panic!("Need precisely one argument to 'narrowable_vec'");
+    }
+    let struct_id = match &args[0] {
+        NestedMeta::Meta(m) => m.name(),
+        NestedMeta::Literal(_) => panic!("Literals not valid attributes to 'narrowable_vec'")
+    };
+    let trait_id = &input.ident;
+    let expanded = quote! {
+        /// A growable collection of heterogeneous implementors of #trait_id, packed contiguously
+        /// into a single allocation.
+        pub struct #struct_id {
+            // Each element is stored as a usize-sized `DynMetadata` header followed by the object
+            // itself, with padding between the two when the object's alignment exceeds a usize's
+            // (see `uoff_for`; a single narrow pointer instead puts any padding before its
+            // header). Elements are packed one after another (aligned to each element's own
+            // requirements). `offsets` records, for each element in push order, the byte
+            // offset of its header within `buf`; that's enough to recompute every element's
+            // metadata and object pointer without walking the buffer.
+            buf: *mut u8,
+            cap: usize,
+            // The alignment `buf` was actually allocated with. Grows monotonically to the
+            // largest alignment any pushed element has required so far, since a relative offset
+            // that's a multiple of some alignment is only meaningful if `buf` itself is aligned
+            // to at least that much.
+            buf_align: usize,
+            len: usize,
+            offsets: ::std::vec::Vec<usize>
+        }
+
+        impl #struct_id {
+            /// Create a new, empty collection. No allocation is performed until the first push.
+            pub fn new() -> Self {
+                #struct_id {
+                    buf: ::std::ptr::NonNull::dangling().as_ptr(),
+                    cap: 0,
+                    buf_align: ::std::mem::align_of::<usize>(),
+                    len: 0,
+                    offsets: ::std::vec::Vec::new()
+                }
+            }
+
+            /// The number of elements currently stored.
+            pub fn len(&self) -> usize {
+                self.offsets.len()
+            }
+
+            /// Whether the collection currently stores no elements.
+            pub fn is_empty(&self) -> bool {
+                self.offsets.is_empty()
+            }
+
+            /// Append `v` to the end of the collection.
+            pub fn push<U>(&mut self, v: U)
+            where
+                *const U: ::std::ops::CoerceUnsized<*const (dyn #trait_id + 'static)>,
+                U: #trait_id + 'static
+            {
+                let (elem_layout, uoff) = ::std::alloc::Layout::new::<usize>().extend(
+                    ::std::alloc::Layout::new::<U>()).unwrap();
+                let elem_layout = elem_layout.pad_to_align();
+                debug_assert_eq!(uoff % ::std::mem::align_of::<usize>(), 0);
+
+                let start = Self::align_up(self.len, elem_layout.align());
+                let end = start + elem_layout.size();
+                let align = ::std::cmp::max(elem_layout.align(), self.buf_align);
+                if end > self.cap || align > self.buf_align {
+                    self.grow(end, align);
+                }
+
+                unsafe {
+                    let header = self.buf.add(start);
+                    let meta = ::core::ptr::metadata(&v as &dyn #trait_id);
+                    ::std::ptr::write(header as *mut ::core::ptr::DynMetadata<dyn #trait_id>, meta);
+                    let objptr = header.add(uoff);
+                    if ::std::mem::size_of::<U>() != 0 {
+                        objptr.copy_from_nonoverlapping(&v as *const U as *const u8,
+                            ::std::mem::size_of::<U>());
+                    }
+                }
+                ::std::mem::forget(v);
+
+                self.offsets.push(start);
+                self.len = end;
+            }
+
+            fn align_up(n: usize, align: usize) -> usize {
+                (n + align - 1) & !(align - 1)
+            }
+
+            /// Grow the backing allocation to hold at least `required` bytes aligned to at least
+            /// `align`, relocating the existing elements (which are just raw bytes plus a stored
+            /// metadata header, so a `copy_nonoverlapping` of the whole region is sufficient --
+            /// there's no pointers into the old buffer to fix up). Called both when `required`
+            /// exceeds the current capacity and when a newly-pushed element needs a bigger
+            /// alignment than the buffer currently guarantees.
+            fn grow(&mut self, required: usize, align: usize) {
+                let new_cap = ::std::cmp::max(required, self.cap * 2).max(
+                    ::std::mem::size_of::<usize>());
+                let new_layout = ::std::alloc::Layout::from_size_align(new_cap, align).unwrap();
+                let new_buf = unsafe { ::std::alloc::alloc(new_layout) };
+                if new_buf.is_null() {
+                    ::std::alloc::handle_alloc_error(new_layout);
+                }
+                if self.cap != 0 {
+                    unsafe {
+                        new_buf.copy_from_nonoverlapping(self.buf, self.len);
+                        let old_layout = ::std::alloc::Layout::from_size_align_unchecked(
+                            self.cap, self.buf_align);
+                        ::std::alloc::dealloc(self.buf, old_layout);
+                    }
+                }
+                self.buf = new_buf;
+                self.cap = new_cap;
+                self.buf_align = align;
+            }
+
+            fn meta_at(&self, idx: usize) -> ::core::ptr::DynMetadata<dyn #trait_id> {
+                unsafe {
+                    ::std::ptr::read(self.buf.add(self.offsets[idx])
+                        as *const ::core::ptr::DynMetadata<dyn #trait_id>)
+                }
+            }
+
+            /// Compute the offset of an element's object from its header, given the
+            /// `DynMetadata` describing it. This mirrors the `Layout::new::<usize>().extend(...)`
+            /// computation done in `push`, so it recovers the real gap even when padding was
+            /// inserted for an over-aligned object -- the gap is only exactly one word when the
+            /// object's alignment doesn't exceed a `usize`'s.
+            fn uoff_for(meta: ::core::ptr::DynMetadata<dyn #trait_id>) -> usize {
+                let object_layout = unsafe {
+                    ::std::alloc::Layout::from_size_align_unchecked(
+                        meta.size_of(), meta.align_of())
+                };
+                let (_, uoff) = ::std::alloc::Layout::new::<usize>().extend(object_layout).unwrap();
+                uoff
+            }
+
+            fn objptr_at(&self, idx: usize, meta: ::core::ptr::DynMetadata<dyn #trait_id>) -> *mut u8 {
+                unsafe { self.buf.add(self.offsets[idx] + Self::uoff_for(meta)) }
+            }
+
+            /// Borrow the element at `idx` as `&dyn #trait_id`, or `None` if out of bounds.
+            pub fn get(&self, idx: usize) -> Option<&dyn #trait_id> {
+                if idx >= self.offsets.len() {
+                    return None;
+                }
+                let meta = self.meta_at(idx);
+                let objptr = self.objptr_at(idx, meta);
+                Some(unsafe { &*::core::ptr::from_raw_parts(objptr as *const (), meta) })
+            }
+
+            /// Mutably borrow the element at `idx` as `&mut dyn #trait_id`, or `None` if out of
+            /// bounds.
+            pub fn get_mut(&mut self, idx: usize) -> Option<&mut dyn #trait_id> {
+                if idx >= self.offsets.len() {
+                    return None;
+                }
+                let meta = self.meta_at(idx);
+                let objptr = self.objptr_at(idx, meta);
+                Some(unsafe { &mut *::core::ptr::from_raw_parts_mut(objptr as *mut (), meta) })
+            }
+
+            /// Try casting the element at `idx` to a concrete struct type `U`, returning
+            /// `Some(...)` if that element is a `U` or `None` otherwise (including when `idx` is
+            /// out of bounds).
+            pub fn downcast<U: #trait_id>(&self, idx: usize) -> Option<&U> {
+                if idx >= self.offsets.len() {
+                    return None;
+                }
+                let t_meta = ::core::ptr::metadata(
+                    ::std::ptr::null::<U>() as *const dyn #trait_id);
+                let meta = self.meta_at(idx);
+                if meta == t_meta {
+                    Some(unsafe { &*(self.objptr_at(idx, meta) as *const U) })
+                } else {
+                    None
+                }
+            }
+
+            /// Iterate over the elements in push order as `&dyn #trait_id`.
+            pub fn iter(&self) -> impl ::std::iter::Iterator<Item = &dyn #trait_id> {
+                (0..self.offsets.len()).map(move |i| self.get(i).unwrap())
+            }
+        }
+
+        impl ::std::ops::Drop for #struct_id {
+            fn drop(&mut self) {
+                for i in 0..self.offsets.len() {
+                    let meta = self.meta_at(i);
+                    let objptr = self.objptr_at(i, meta);
+                    let fatptr: *mut dyn #trait_id =
+                        ::core::ptr::from_raw_parts_mut(objptr as *mut (), meta);
+                    unsafe { ::std::ptr::drop_in_place(fatptr) };
+                }
+                if self.cap != 0 {
+                    unsafe {
+                        let layout = ::std::alloc::Layout::from_size_align_unchecked(
+                            self.cap, self.buf_align);
+                        ::std::alloc::dealloc(self.buf, layout);
+                    }
                 }
             }
         }
@@ -175,16 +489,32 @@ pub fn narrowable_rboehm(args: TokenStream, input: TokenStream) -> TokenStream {
     let expanded = quote! {
         /// A narrow pointer to #trait_id.
         pub struct #struct_id {
-            // This struct points to a vtable pointer followed by an object. In other words, on a
-            // 64 bit machine the layout is (in bytes):
-            //   0..7: vtable
+            // This struct points at the base of the allocation, which always begins with a
+            // usize-sized `DynMetadata` slot. The object follows, but -- unlike the non-GC
+            // `narrowable` pointer -- its offset from the base isn't fixed at `size_of::<usize>()`:
+            // an over-aligned object needs padding between the metadata and the object to satisfy
+            // its alignment. That offset is always recoverable from the metadata itself (it
+            // records the object's size and alignment), via `Self::uoff_for`, so the layout below
+            // is only descriptive of the common (no padding) case:
+            //   0..7: metadata
             //   8..: object
-            // This is an inflexible layout, since we can only support structs whose alignment is
-            // the same or less than a usize's.
             vtable: *mut u8
         }
 
         impl #struct_id {
+            /// Compute the offset of the object from the base of the allocation, given the
+            /// `DynMetadata` describing it. This mirrors the `Layout::new::<usize>().extend(...)`
+            /// computation done at construction time, so it recovers the same offset even when
+            /// padding was inserted for an over-aligned object.
+            fn uoff_for(meta: ::core::ptr::DynMetadata<dyn #trait_id>) -> usize {
+                let object_layout = unsafe {
+                    ::std::alloc::Layout::from_size_align_unchecked(
+                        meta.size_of(), meta.align_of())
+                };
+                let (_, uoff) = ::std::alloc::Layout::new::<usize>().extend(object_layout).unwrap();
+                uoff
+            }
+
             /// Create a new narrow pointer to #trait_id.
             pub fn new<U>(v: U) -> ::rboehm::Gc<Self>
             where
@@ -193,17 +523,17 @@ pub fn narrowable_rboehm(args: TokenStream, input: TokenStream) -> TokenStream {
             {
                 let (layout, uoff) = ::std::alloc::Layout::new::<usize>().extend(
                     ::std::alloc::Layout::new::<U>()).unwrap();
-                // Check that we've not been given an object whose alignment
-                // exceeds that of a usize.
-                debug_assert_eq!(uoff, ::std::mem::size_of::<usize>());
+                debug_assert_eq!(
+                    ::std::mem::size_of::<::core::ptr::DynMetadata<dyn #trait_id>>(),
+                    ::std::mem::size_of::<usize>()
+                );
 
                 let gc = ::rboehm::Gc::<#struct_id>::new_from_layout(layout);
                 let baseptr = ::rboehm::Gc::into_raw(gc);
                 unsafe {
                     let objptr = (baseptr as *mut u8).add(uoff);
-                    let t: &dyn #trait_id = &v;
-                    let vtable = ::std::mem::transmute::<*const dyn #trait_id, (usize, usize)>(t).1;
-                    ::std::ptr::write(baseptr as *mut usize, vtable);
+                    let meta = ::core::ptr::metadata(&v as &dyn #trait_id);
+                    ::std::ptr::write(baseptr as *mut ::core::ptr::DynMetadata<dyn #trait_id>, meta);
 
                     if ::std::mem::size_of::<U>() != 0 {
                         objptr.copy_from_nonoverlapping(&v as *const U as *const u8,
@@ -229,17 +559,19 @@ pub fn narrowable_rboehm(args: TokenStream, input: TokenStream) -> TokenStream {
                 where F: FnOnce(*mut U)
             {
                 let (layout, uoff) = ::std::alloc::Layout::new::<usize>().extend(layout).unwrap();
-                // Check that we've not been given an object whose alignment
-                // exceeds that of a usize.
-                debug_assert_eq!(uoff, ::std::mem::size_of::<usize>());
+                // Place the object at the offset `U`'s own alignment implies, since that is what
+                // `uoff_for` recovers from the metadata. `layout`'s alignment is at least `U`'s,
+                // so this offset is never beyond the one `layout` reserved.
+                let (_, uoff) = ::std::alloc::Layout::new::<usize>().extend(
+                    ::std::alloc::Layout::new::<U>()).unwrap();
 
                 let gc = ::rboehm::Gc::<Self>::new_from_layout(layout);
                 let baseptr = ::rboehm::Gc::into_raw(gc);
                 unsafe {
                     let objptr = (baseptr as *mut u8).add(uoff);
                     let t: *const dyn #trait_id = objptr as *const U;
-                    let vtable = ::std::mem::transmute::<*const dyn #trait_id, (usize, usize)>(t).1;
-                    ::std::ptr::write(baseptr as *mut usize, vtable);
+                    let meta = ::core::ptr::metadata(t);
+                    ::std::ptr::write(baseptr as *mut ::core::ptr::DynMetadata<dyn #trait_id>, meta);
                     init(objptr as *mut U);
                     gc.assume_init()
                 }
@@ -256,8 +588,10 @@ pub fn narrowable_rboehm(args: TokenStream, input: TokenStream) -> TokenStream {
             pub unsafe fn recover_gc<T: #trait_id>(o: Gc<T>) -> ::rboehm::Gc<#struct_id> {
                 unsafe {
                     let objptr = Gc::into_raw(o);
-                    let baseptr = (objptr as *const usize).sub(1);
-                    Gc::from_raw(baseptr as *const u8 as *const #struct_id)
+                    let (_, uoff) = ::std::alloc::Layout::new::<usize>().extend(
+                        ::std::alloc::Layout::new::<T>()).unwrap();
+                    let baseptr = (objptr as *const u8).sub(uoff);
+                    Gc::from_raw(baseptr as *const #struct_id)
                 }
             }
 
@@ -265,17 +599,16 @@ pub fn narrowable_rboehm(args: TokenStream, input: TokenStream) -> TokenStream {
             /// `U`, returning `Some(...)` if this narrow trait object has
             /// stored an object of type `U` or `None` otherwise.
             pub fn downcast<U: #trait_id>(&self) -> Option<Gc<U>> {
-                let t_vtable = {
-                    let t: *const dyn #trait_id = ::std::ptr::null() as *const U;
-                    unsafe { ::std::mem::transmute::<*const dyn #trait_id, (usize, usize)>(t) }.1
-                };
+                let t_meta = ::core::ptr::metadata(
+                    ::std::ptr::null::<U>() as *const dyn #trait_id);
 
-                let vtable = unsafe {
-                    ::std::ptr::read(self as *const _ as *const usize)
+                let meta = unsafe {
+                    ::std::ptr::read(self as *const _ as *const ::core::ptr::DynMetadata<dyn #trait_id>)
                 };
 
-                if t_vtable == vtable {
-                    let objptr = unsafe { (self as *const _ as *const usize).add(1) };
+                if t_meta == meta {
+                    let uoff = Self::uoff_for(meta);
+                    let objptr = unsafe { (self as *const _ as *const u8).add(uoff) };
                     Some(unsafe { Gc::from_raw(objptr as *const U) })
                 } else {
                     None
@@ -288,10 +621,10 @@ pub fn narrowable_rboehm(args: TokenStream, input: TokenStream) -> TokenStream {
 
             fn deref(&self) -> &(dyn #trait_id + 'static) {
                 unsafe {
-                    let vtable = ::std::ptr::read(self as *const _ as *const usize as *mut usize);
-                    let objptr = (self as *const _ as *const usize).add(1);
-                    ::std::mem::transmute::<(*const _, usize), &dyn #trait_id>(
-                        (objptr, vtable))
+                    let meta = ::std::ptr::read(
+                        self as *const _ as *const ::core::ptr::DynMetadata<dyn #trait_id>);
+                    let objptr = (self as *const _ as *const u8).add(Self::uoff_for(meta));
+                    &*::core::ptr::from_raw_parts(objptr as *const (), meta)
                 }
             }
         }
@@ -299,14 +632,14 @@ pub fn narrowable_rboehm(args: TokenStream, input: TokenStream) -> TokenStream {
         impl ::std::ops::Drop for #struct_id {
             fn drop(&mut self) {
                 let fatptr = unsafe {
-                    let vtable = ::std::ptr::read(self as *const _ as *const usize as *mut usize);
-                    let objptr = (self as *const _ as *const usize).add(1);
-                    ::std::mem::transmute::<(*const _, usize), &mut dyn #trait_id>(
-                        (objptr, vtable))
+                    let meta = ::std::ptr::read(
+                        self as *const _ as *const ::core::ptr::DynMetadata<dyn #trait_id>);
+                    let objptr = (self as *const _ as *const u8).add(Self::uoff_for(meta));
+                    ::core::ptr::from_raw_parts_mut::<dyn #trait_id>(objptr as *mut (), meta)
                 };
 
                 // Call `drop` on the trait object before deallocating memory.
-                unsafe { ::std::ptr::drop_in_place(fatptr as *mut dyn #trait_id) };
+                unsafe { ::std::ptr::drop_in_place(fatptr) };
             }
         }
 
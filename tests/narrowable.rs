@@ -0,0 +1,96 @@
+#![feature(ptr_metadata)]
+#![feature(coerce_unsized, unsize)]
+#![feature(allocator_api)]
+
+use natrob::narrowable;
+
+#[narrowable(ShapeRef)]
+trait Shape {
+    fn area(&self) -> f64;
+    fn scale(&mut self, factor: f64);
+}
+
+struct Square(f64);
+impl Shape for Square {
+    fn area(&self) -> f64 {
+        self.0 * self.0
+    }
+    fn scale(&mut self, factor: f64) {
+        self.0 *= factor;
+    }
+}
+
+struct Circle(f64);
+impl Shape for Circle {
+    fn area(&self) -> f64 {
+        std::f64::consts::PI * self.0 * self.0
+    }
+    fn scale(&mut self, factor: f64) {
+        self.0 *= factor;
+    }
+}
+
+#[test]
+fn deref_dispatches_to_the_stored_object() {
+    let square = ShapeRef::new(Square(3.0));
+    assert!((square.area() - 9.0).abs() < 1e-9);
+
+    let circle = ShapeRef::new(Circle(2.0));
+    assert!((circle.area() - std::f64::consts::PI * 4.0).abs() < 1e-9);
+}
+
+#[test]
+fn deref_mut_dispatches_to_the_stored_object() {
+    let mut square = ShapeRef::new(Square(3.0));
+    square.scale(2.0);
+    assert!((square.area() - 36.0).abs() < 1e-9);
+}
+
+#[test]
+fn downcast_matches_only_the_stored_concrete_type() {
+    let square = ShapeRef::new(Square(3.0));
+    assert!(square.downcast::<Square>().is_some());
+    assert!(square.downcast::<Circle>().is_none());
+    assert!((square.downcast::<Square>().unwrap().0 - 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn drop_runs_the_stored_objects_destructor_exactly_once() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Counted(Rc<RefCell<usize>>);
+    impl Shape for Counted {
+        fn area(&self) -> f64 {
+            0.0
+        }
+        fn scale(&mut self, _factor: f64) {}
+    }
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            *self.0.borrow_mut() += 1;
+        }
+    }
+
+    let count = Rc::new(RefCell::new(0));
+    {
+        let _p = ShapeRef::new(Counted(count.clone()));
+        assert_eq!(*count.borrow(), 0);
+    }
+    assert_eq!(*count.borrow(), 1);
+}
+
+#[test]
+fn zero_sized_object_round_trips() {
+    struct Nothing;
+    impl Shape for Nothing {
+        fn area(&self) -> f64 {
+            0.0
+        }
+        fn scale(&mut self, _factor: f64) {}
+    }
+
+    let p = ShapeRef::new(Nothing);
+    assert_eq!(p.area(), 0.0);
+    assert!(p.downcast::<Nothing>().is_some());
+}
@@ -0,0 +1,65 @@
+#![feature(ptr_metadata)]
+#![feature(coerce_unsized, unsize)]
+#![feature(allocator_api)]
+
+use natrob::narrowable;
+use std::alloc::{AllocError, Allocator, Global, Layout};
+use std::ptr::NonNull;
+
+#[narrowable(AnimalRef)]
+trait Animal {
+    fn speak(&self) -> &'static str;
+}
+
+struct Dog;
+impl Animal for Dog {
+    fn speak(&self) -> &'static str {
+        "woof"
+    }
+}
+
+struct Cat;
+impl Animal for Cat {
+    fn speak(&self) -> &'static str {
+        "meow"
+    }
+}
+
+/// Forwards to `Global`, but is a distinct type so `new_in` is demonstrably not hard-wired to
+/// `Global`.
+#[derive(Clone, Copy, Default)]
+struct OtherAllocator;
+
+unsafe impl Allocator for OtherAllocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        Global.allocate(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { Global.deallocate(ptr, layout) }
+    }
+}
+
+#[test]
+fn new_in_allocates_through_a_custom_allocator() {
+    let p = AnimalRef::new_in(Dog, OtherAllocator);
+    assert_eq!(p.speak(), "woof");
+    assert!(p.downcast::<Dog>().is_some());
+    assert!(p.downcast::<Cat>().is_none());
+}
+
+#[test]
+fn from_raw_parts_reconstructs_a_pointer_built_by_new() {
+    let p = AnimalRef::new(Dog);
+    assert_eq!(p.speak(), "woof");
+
+    // `from_raw_parts` is the escape hatch for callers who already hold the object pointer
+    // `new`/`new_in` would have produced, plus the allocator it came from. Casting away the
+    // `dyn Animal` fat pointer's metadata recovers exactly that object pointer.
+    let objptr = &*p as *const dyn Animal as *const u8 as *mut u8;
+    std::mem::forget(p);
+
+    let rebuilt = unsafe { AnimalRef::from_raw_parts(objptr, Global) };
+    assert_eq!(rebuilt.speak(), "woof");
+    assert!(rebuilt.downcast::<Dog>().is_some());
+}
@@ -0,0 +1,74 @@
+#![feature(ptr_metadata)]
+#![feature(coerce_unsized, unsize)]
+#![feature(allocator_api)]
+
+use natrob::narrowable;
+
+#[narrowable(ShapeRef)]
+trait Shape {
+    fn area(&self) -> f64;
+}
+
+struct Square(f64);
+impl Shape for Square {
+    fn area(&self) -> f64 {
+        self.0 * self.0
+    }
+}
+
+struct Circle(f64);
+impl Shape for Circle {
+    fn area(&self) -> f64 {
+        std::f64::consts::PI * self.0 * self.0
+    }
+}
+
+#[test]
+fn downcast_mut_allows_mutating_the_concrete_type() {
+    let mut p = ShapeRef::new(Square(3.0));
+    p.downcast_mut::<Square>().unwrap().0 = 5.0;
+    assert!((p.area() - 25.0).abs() < 1e-9);
+    assert!(p.downcast_mut::<Circle>().is_none());
+}
+
+#[test]
+fn into_inner_recovers_the_owned_value_on_a_match() {
+    let p = ShapeRef::new(Square(4.0));
+    let square = p.into_inner::<Square>().ok().expect("Square should downcast");
+    assert_eq!(square.0, 4.0);
+}
+
+#[test]
+fn into_inner_returns_self_unchanged_on_a_mismatch() {
+    let p = ShapeRef::new(Square(4.0));
+    let p = p.into_inner::<Circle>().err().expect("Circle downcast should fail");
+    assert!((p.area() - 16.0).abs() < 1e-9);
+    assert!(p.downcast::<Square>().is_some());
+}
+
+#[test]
+fn into_inner_does_not_run_the_recovered_value_s_destructor() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Counted(Rc<RefCell<usize>>);
+    impl Shape for Counted {
+        fn area(&self) -> f64 {
+            0.0
+        }
+    }
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            *self.0.borrow_mut() += 1;
+        }
+    }
+
+    let count = Rc::new(RefCell::new(0));
+    let p = ShapeRef::new(Counted(count.clone()));
+    let inner = p.into_inner::<Counted>().ok().expect("Counted should downcast");
+    // The narrow pointer's backing storage was freed without running `Counted::drop`; the value
+    // now lives in `inner` and is only dropped once, when it goes out of scope below.
+    assert_eq!(*count.borrow(), 0);
+    drop(inner);
+    assert_eq!(*count.borrow(), 1);
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "rboehm")]
+#![feature(ptr_metadata)]
+#![feature(coerce_unsized, unsize)]
+
+use natrob::narrowable_rboehm;
+use rboehm::Gc;
+
+#[narrowable_rboehm(AnimalRef)]
+trait Animal {
+    fn speak(&self) -> &'static str;
+}
+
+#[repr(align(16))]
+struct Cat {
+    legs: u8,
+}
+impl Animal for Cat {
+    fn speak(&self) -> &'static str {
+        "meow"
+    }
+}
+
+struct Mouse;
+impl Animal for Mouse {
+    fn speak(&self) -> &'static str {
+        "squeak"
+    }
+}
+
+#[test]
+fn over_aligned_object_round_trips() {
+    let gc: Gc<AnimalRef> = AnimalRef::new(Cat { legs: 4 });
+    assert_eq!(gc.speak(), "meow");
+
+    let cat_gc = gc.downcast::<Cat>().expect("downcast to Cat should succeed");
+    assert!(gc.downcast::<Mouse>().is_none());
+
+    let ptr = &*cat_gc as *const Cat as usize;
+    assert_eq!(ptr % std::mem::align_of::<Cat>(), 0);
+    assert_eq!(cat_gc.legs, 4);
+
+    let recovered = unsafe { AnimalRef::recover_gc(cat_gc) };
+    assert_eq!(recovered.speak(), "meow");
+}
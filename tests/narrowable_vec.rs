@@ -0,0 +1,83 @@
+#![feature(ptr_metadata)]
+#![feature(coerce_unsized, unsize)]
+
+use natrob::narrowable_vec;
+
+#[narrowable_vec(ShapeVec)]
+trait Shape {
+    fn area(&self) -> f64;
+}
+
+struct Square(f64);
+impl Shape for Square {
+    fn area(&self) -> f64 {
+        self.0 * self.0
+    }
+}
+
+#[repr(align(32))]
+struct AlignedCircle {
+    r: f64,
+}
+impl Shape for AlignedCircle {
+    fn area(&self) -> f64 {
+        std::f64::consts::PI * self.r * self.r
+    }
+}
+
+struct Dot;
+impl Shape for Dot {
+    fn area(&self) -> f64 {
+        0.0
+    }
+}
+
+#[test]
+fn push_mixed_size_and_alignment_round_trips() {
+    let mut v = ShapeVec::new();
+    v.push(Square(2.0));
+    v.push(AlignedCircle { r: 1.0 });
+    v.push(Dot);
+    // Push enough more elements to force at least one `grow()`.
+    for i in 0..32 {
+        v.push(Square(i as f64));
+    }
+
+    assert_eq!(v.len(), 35);
+    assert!((v.get(0).unwrap().area() - 4.0).abs() < 1e-9);
+    assert!(v.downcast::<AlignedCircle>(1).is_some());
+    assert!(v.downcast::<Square>(1).is_none());
+
+    let circle = v.downcast::<AlignedCircle>(1).unwrap();
+    assert_eq!((circle as *const AlignedCircle as usize) % std::mem::align_of::<AlignedCircle>(), 0);
+
+    assert_eq!(v.iter().count(), 35);
+}
+
+#[test]
+fn drop_across_growth_runs_every_destructor_exactly_once() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Counted(Rc<RefCell<usize>>);
+    impl Shape for Counted {
+        fn area(&self) -> f64 {
+            0.0
+        }
+    }
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            *self.0.borrow_mut() += 1;
+        }
+    }
+
+    let count = Rc::new(RefCell::new(0));
+    {
+        let mut v = ShapeVec::new();
+        for _ in 0..64 {
+            v.push(Counted(count.clone()));
+        }
+        // Dropped at the end of this scope.
+    }
+    assert_eq!(*count.borrow(), 64);
+}